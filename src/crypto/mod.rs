@@ -0,0 +1,4 @@
+pub mod auth;
+pub mod passkey;
+pub mod token;
+pub mod vault;
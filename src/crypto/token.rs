@@ -1,15 +1,81 @@
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use blake2::digest::{
+    consts::{U32, U56},
+    FixedOutput, KeyInit, Update,
+};
+use blake2::Blake2bMac;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::XChaCha20;
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use time::{Duration, OffsetDateTime};
 
 /// PASETO v4.public Implementation (Simplified)
 /// Format: v4.public.[message].[signature]
 /// Message: base64url(json_claims)
 /// Signature: base64url(Ed25519_Sign(m, version_header || m))
+/// Footer (optional): base64url(footer), appended as a further dot-separated
+/// segment after the signature. The footer is authenticated (fed through PAE) but travels in
+/// cleartext, so callers can use it for key IDs / routing hints.
 
 const HEADER: &[u8] = b"v4.public.";
 
-pub fn sign_paseto<T: Serialize>(claims: &T, private_key_hex: &str) -> Result<String, String> {
+/// PASETO v4.local Implementation
+/// Format: v4.local.[base64(nonce || ciphertext || tag)][.footer]
+/// Confidential, symmetrically-keyed tokens: the claims are encrypted with
+/// XChaCha20 under a key derived from the caller's 32-byte secret, and
+/// authenticated with a keyed BLAKE2b tag over PAE(header, nonce, ciphertext, footer).
+const HEADER_LOCAL: &[u8] = b"v4.local.";
+
+/// Registered claims every PASETO token may carry (RFC 3339 timestamps,
+/// per the PASETO spec). All fields are optional since callers choose via
+/// `ValidationRules` which of them are actually enforced.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegisteredClaims {
+    exp: Option<String>,
+    nbf: Option<String>,
+    iat: Option<String>,
+    aud: Option<String>,
+    iss: Option<String>,
+}
+
+/// Controls which registered claims `verify_paseto` enforces, and how much
+/// slack is allowed for clock drift between issuer and verifier.
+#[derive(Debug, Clone)]
+pub struct ValidationRules {
+    pub validate_exp: bool,
+    pub validate_nbf: bool,
+    pub validate_iat: bool,
+    /// Leeway applied to `exp`/`nbf`/`iat` comparisons to tolerate clock skew.
+    pub clock_skew_leeway: Duration,
+    /// How far into the future an `iat` may plausibly sit before it's
+    /// rejected as implausible (guards against a forged/garbage `iat`).
+    pub max_iat_drift: Duration,
+    pub expected_audience: Option<String>,
+    pub expected_issuer: Option<String>,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        Self {
+            validate_exp: true,
+            validate_nbf: true,
+            validate_iat: true,
+            clock_skew_leeway: Duration::seconds(60),
+            max_iat_drift: Duration::minutes(5),
+            expected_audience: None,
+            expected_issuer: None,
+        }
+    }
+}
+
+pub fn sign_paseto<T: Serialize>(
+    claims: &T,
+    private_key_hex: &str,
+    footer: Option<&[u8]>,
+) -> Result<String, String> {
     // 1. Decode Private Key
     let key_bytes = hex::decode(private_key_hex).map_err(|e| e.to_string())?;
 
@@ -32,38 +98,41 @@ pub fn sign_paseto<T: Serialize>(claims: &T, private_key_hex: &str) -> Result<St
 
     // 3. Prepare PASETO Pre-Authentication Encoding (PAE)
     // PAE(header, m, footer) = LE64(num_pieces) | LE64(len(header)) | header | LE64(len(m)) | m | ...
-    // Here: PAE("v4.public.", m, "")
-    // Actually, v4.public format is: v4.public.base64(m).base64(sig)
-    // BUT efficient implementations sign: PAE(header, m, footer)
-    // Let's stick to the spec: https://github.com/paseto-standard/paseto-spec/blob/master/docs/01-Protocol-Versions/Version4.md#sign
+    // Here: PAE("v4.public.", m, footer) -- footer is empty unless the caller supplies one.
+    // https://github.com/paseto-standard/paseto-spec/blob/master/docs/01-Protocol-Versions/Version4.md#sign
 
-    let m2 = pae(HEADER, m, &[]); // footer is empty
+    let footer = footer.unwrap_or(&[]);
+    let m2 = pae(&[HEADER, m, footer]);
 
     // 4. Sign
     let signature = signing_key.sign(&m2);
 
     // 5. Assemble Token
-    // token = header || base64(m) || . || base64(sig)
-    // Note: The standard actually says "v4.public." || base64(m) || base64(sig) NO.
-    // Wait, let's double check the spec carefully.
-    // "The content of the token is the message m, signed."
-    // Format: version || . || purpose || . || base64url(m) || . || base64url(sig)
-    // Standard: v4.public.payload.signature (where payload and signature are base64url)
+    // token = header || base64(m) || . || base64(sig) [ || . || base64(footer) ]
+    // Standard: v4.public.payload.signature[.footer]
 
     let b64_m = URL_SAFE_NO_PAD.encode(m);
     let b64_sig = URL_SAFE_NO_PAD.encode(signature.to_bytes());
 
-    Ok(format!(
+    let mut token = format!(
         "{}{}.{}",
         std::str::from_utf8(HEADER).unwrap(),
         b64_m,
         b64_sig
-    ))
+    );
+    if !footer.is_empty() {
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(footer));
+    }
+
+    Ok(token)
 }
 
 pub fn verify_paseto<T: for<'a> Deserialize<'a>>(
     token: &str,
     public_key_hex: &str,
+    footer: Option<&[u8]>,
+    rules: &ValidationRules,
 ) -> Result<T, String> {
     // 1. Check Header
     let header_str = std::str::from_utf8(HEADER).unwrap();
@@ -71,15 +140,30 @@ pub fn verify_paseto<T: for<'a> Deserialize<'a>>(
         return Err("Invalid token header".to_string());
     }
 
-    // 2. Split remainder
+    // 2. Split remainder. A footer is optional, so we accept either
+    // "payload.signature" or "payload.signature.footer".
     let remainder = &token[header_str.len()..];
     let parts: Vec<&str> = remainder.split('.').collect();
-    if parts.len() != 2 {
+    if parts.len() != 2 && parts.len() != 3 {
         return Err("Invalid token format".to_string());
     }
 
     let b64_m = parts[0];
     let b64_sig = parts[1];
+    let footer_bytes = if parts.len() == 3 {
+        URL_SAFE_NO_PAD
+            .decode(parts[2])
+            .map_err(|_| "Invalid base64 footer".to_string())?
+    } else {
+        Vec::new()
+    };
+
+    // The caller-supplied expected footer (if any) must match what's on the wire.
+    if let Some(expected_footer) = footer {
+        if expected_footer != footer_bytes.as_slice() {
+            return Err("Footer mismatch".to_string());
+        }
+    }
 
     // 3. Decode
     let m = URL_SAFE_NO_PAD
@@ -104,31 +188,225 @@ pub fn verify_paseto<T: for<'a> Deserialize<'a>>(
 
     // 5. Verify Signature
     // Must reconstruct PAE(header, m, footer)
-    let m2 = pae(HEADER, &m, &[]);
+    let m2 = pae(&[HEADER, &m, &footer_bytes]);
     verifying_key
         .verify(&m2, &signature)
         .map_err(|e| e.to_string())?;
 
-    // 6. Deserialize Payload
+    // 6. Enforce registered claims before handing the payload to the caller.
+    validate_registered_claims(&m, rules)?;
+
+    // 7. Deserialize Payload
     let claims: T = serde_json::from_slice(&m).map_err(|e| e.to_string())?;
 
     Ok(claims)
 }
 
-/// Pre-Authentication Encoding (PAE)
-fn pae(header: &[u8], m: &[u8], footer: &[u8]) -> Vec<u8> {
-    let mut output = Vec::new();
+/// Checks `exp`/`nbf`/`iat`/`aud`/`iss` against `rules`. When a `validate_*`
+/// flag is set, the corresponding claim must be present -- a caller that
+/// wants to issue non-expiring tokens has to explicitly set `validate_exp =
+/// false` rather than simply omitting `exp` from its claims.
+fn validate_registered_claims(payload: &[u8], rules: &ValidationRules) -> Result<(), String> {
+    let registered: RegisteredClaims =
+        serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+    let now = OffsetDateTime::now_utc();
 
-    output.extend_from_slice(&le64(3)); // Number of pieces (header, m, footer)
+    if rules.validate_exp {
+        let exp = registered
+            .exp
+            .as_deref()
+            .ok_or_else(|| "Token is missing required `exp` claim".to_string())?;
+        let exp = parse_timestamp(exp, "exp")?;
+        if now - rules.clock_skew_leeway > exp {
+            return Err("Token has expired".to_string());
+        }
+    }
 
-    output.extend_from_slice(&le64(header.len() as u64));
-    output.extend_from_slice(header);
+    if rules.validate_nbf {
+        let nbf = registered
+            .nbf
+            .as_deref()
+            .ok_or_else(|| "Token is missing required `nbf` claim".to_string())?;
+        let nbf = parse_timestamp(nbf, "nbf")?;
+        if now + rules.clock_skew_leeway < nbf {
+            return Err("Token is not yet valid".to_string());
+        }
+    }
 
-    output.extend_from_slice(&le64(m.len() as u64));
-    output.extend_from_slice(m);
+    if rules.validate_iat {
+        let iat = registered
+            .iat
+            .as_deref()
+            .ok_or_else(|| "Token is missing required `iat` claim".to_string())?;
+        let iat = parse_timestamp(iat, "iat")?;
+        if iat > now + rules.clock_skew_leeway + rules.max_iat_drift {
+            return Err("Token was issued implausibly far in the future".to_string());
+        }
+    }
+
+    if let Some(expected_aud) = &rules.expected_audience {
+        match &registered.aud {
+            Some(aud) if aud == expected_aud => {}
+            _ => return Err("Audience mismatch".to_string()),
+        }
+    }
+
+    if let Some(expected_iss) = &rules.expected_issuer {
+        match &registered.iss {
+            Some(iss) if iss == expected_iss => {}
+            _ => return Err("Issuer mismatch".to_string()),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_timestamp(value: &str, claim: &str) -> Result<OffsetDateTime, String> {
+    OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+        .map_err(|_| format!("Invalid `{claim}` timestamp"))
+}
 
-    output.extend_from_slice(&le64(footer.len() as u64));
-    output.extend_from_slice(footer);
+/// Encrypts `claims` into a `v4.local` token under a 32-byte symmetric key.
+pub fn encrypt_local<T: Serialize>(
+    claims: &T,
+    key: &[u8; 32],
+    footer: Option<&[u8]>,
+) -> Result<String, String> {
+    let json_claims = serde_json::to_string(claims).map_err(|e| e.to_string())?;
+    let m = json_claims.as_bytes();
+
+    let mut nonce = [0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+
+    let (enc_key, stream_nonce, auth_key) = derive_local_keys(key, &nonce);
+
+    let mut ciphertext = m.to_vec();
+    let mut cipher = XChaCha20::new(&enc_key.into(), &stream_nonce.into());
+    cipher.apply_keystream(&mut ciphertext);
+
+    let footer = footer.unwrap_or(&[]);
+    let pre_auth = pae(&[HEADER_LOCAL, &nonce, &ciphertext, footer]);
+    let tag = blake2b_mac::<U32>(&auth_key, &pre_auth);
+
+    let mut payload = Vec::with_capacity(nonce.len() + ciphertext.len() + tag.len());
+    payload.extend_from_slice(&nonce);
+    payload.extend_from_slice(&ciphertext);
+    payload.extend_from_slice(&tag);
+
+    let mut token = format!(
+        "{}{}",
+        std::str::from_utf8(HEADER_LOCAL).unwrap(),
+        URL_SAFE_NO_PAD.encode(payload)
+    );
+    if !footer.is_empty() {
+        token.push('.');
+        token.push_str(&URL_SAFE_NO_PAD.encode(footer));
+    }
+
+    Ok(token)
+}
+
+/// Decrypts a `v4.local` token, verifying its tag before releasing the
+/// claims, and applies the same registered-claim validation as `verify_paseto`.
+pub fn decrypt_local<T: for<'a> Deserialize<'a>>(
+    token: &str,
+    key: &[u8; 32],
+    footer: Option<&[u8]>,
+    rules: &ValidationRules,
+) -> Result<T, String> {
+    let header_str = std::str::from_utf8(HEADER_LOCAL).unwrap();
+    if !token.starts_with(header_str) {
+        return Err("Invalid token header".to_string());
+    }
+
+    let remainder = &token[header_str.len()..];
+    let (b64_payload, footer_bytes) = match remainder.find('.') {
+        Some(idx) => {
+            let decoded_footer = URL_SAFE_NO_PAD
+                .decode(&remainder[idx + 1..])
+                .map_err(|_| "Invalid base64 footer".to_string())?;
+            (&remainder[..idx], decoded_footer)
+        }
+        None => (remainder, Vec::new()),
+    };
+
+    if let Some(expected_footer) = footer {
+        if expected_footer != footer_bytes.as_slice() {
+            return Err("Footer mismatch".to_string());
+        }
+    }
+
+    let payload = URL_SAFE_NO_PAD
+        .decode(b64_payload)
+        .map_err(|_| "Invalid base64 payload".to_string())?;
+    if payload.len() < 32 + 32 {
+        return Err("Invalid token payload length".to_string());
+    }
+
+    let nonce: [u8; 32] = payload[0..32].try_into().unwrap();
+    let ciphertext = &payload[32..payload.len() - 32];
+    let tag = &payload[payload.len() - 32..];
+
+    let (enc_key, stream_nonce, auth_key) = derive_local_keys(key, &nonce);
+
+    let pre_auth = pae(&[HEADER_LOCAL, &nonce, ciphertext, &footer_bytes]);
+    let expected_tag = blake2b_mac::<U32>(&auth_key, &pre_auth);
+
+    if expected_tag.as_slice().ct_eq(tag).unwrap_u8() != 1 {
+        return Err("Invalid authentication tag".to_string());
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    let mut cipher = XChaCha20::new(&enc_key.into(), &stream_nonce.into());
+    cipher.apply_keystream(&mut plaintext);
+
+    validate_registered_claims(&plaintext, rules)?;
+
+    let claims: T = serde_json::from_slice(&plaintext).map_err(|e| e.to_string())?;
+    Ok(claims)
+}
+
+/// Derives the `v4.local` encryption key, stream nonce and authentication
+/// key from the caller's symmetric key and the per-token random nonce.
+fn derive_local_keys(key: &[u8; 32], nonce: &[u8; 32]) -> ([u8; 32], [u8; 24], [u8; 32]) {
+    let mut enc_info = Vec::with_capacity(22 + nonce.len());
+    enc_info.extend_from_slice(b"paseto-encryption-key");
+    enc_info.extend_from_slice(nonce);
+    let tmp = blake2b_mac::<U56>(key, &enc_info);
+    let enc_key: [u8; 32] = tmp[0..32].try_into().unwrap();
+    let stream_nonce: [u8; 24] = tmp[32..56].try_into().unwrap();
+
+    let mut auth_info = Vec::with_capacity(24 + nonce.len());
+    auth_info.extend_from_slice(b"paseto-auth-key-for-aead");
+    auth_info.extend_from_slice(nonce);
+    let auth_key: [u8; 32] = blake2b_mac::<U32>(key, &auth_info).into();
+
+    (enc_key, stream_nonce, auth_key)
+}
+
+/// Keyed BLAKE2b MAC, generic over the output size (used for both the
+/// 56-byte key-derivation blob and the 32-byte auth tag).
+fn blake2b_mac<OutSize>(key: &[u8], data: &[u8]) -> blake2::digest::generic_array::GenericArray<u8, OutSize>
+where
+    OutSize: blake2::digest::generic_array::ArrayLength<u8>
+        + blake2::digest::typenum::IsLessOrEqual<blake2::digest::consts::U64>,
+    blake2::digest::typenum::LeEq<OutSize, blake2::digest::consts::U64>: blake2::digest::typenum::NonZero,
+{
+    let mut mac = Blake2bMac::<OutSize>::new_from_slice(key).expect("key length is valid for HMAC");
+    mac.update(data);
+    mac.finalize_fixed()
+}
+
+/// Pre-Authentication Encoding (PAE) over an arbitrary number of pieces:
+/// LE64(num_pieces) || LE64(len(piece_0)) || piece_0 || LE64(len(piece_1)) || piece_1 || ...
+fn pae(pieces: &[&[u8]]) -> Vec<u8> {
+    let mut output = Vec::new();
+
+    output.extend_from_slice(&le64(pieces.len() as u64));
+    for piece in pieces {
+        output.extend_from_slice(&le64(piece.len() as u64));
+        output.extend_from_slice(piece);
+    }
 
     output
 }
@@ -136,3 +414,114 @@ fn pae(header: &[u8], m: &[u8], footer: &[u8]) -> Vec<u8> {
 fn le64(n: u64) -> [u8; 8] {
     n.to_le_bytes()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Claims {
+        sub: String,
+    }
+
+    fn no_time_checks() -> ValidationRules {
+        ValidationRules {
+            validate_exp: false,
+            validate_nbf: false,
+            validate_iat: false,
+            ..ValidationRules::default()
+        }
+    }
+
+    /// Flips one bit somewhere inside the token's decoded `nonce ||
+    /// ciphertext || tag` payload, leaving any footer segment untouched.
+    /// `offset_from_end` is taken modulo the payload length so callers can
+    /// target the tag (small offsets) or the ciphertext (larger offsets)
+    /// without knowing the exact payload length up front.
+    fn corrupt_local_payload(token: &str, offset_from_end: usize) -> String {
+        let header_str = std::str::from_utf8(HEADER_LOCAL).unwrap();
+        let remainder = &token[header_str.len()..];
+        let (b64_payload, rest) = match remainder.find('.') {
+            Some(idx) => (&remainder[..idx], &remainder[idx..]),
+            None => (remainder, ""),
+        };
+
+        let mut payload = URL_SAFE_NO_PAD.decode(b64_payload).unwrap();
+        let len = payload.len();
+        payload[len - 1 - (offset_from_end % len)] ^= 0x01;
+
+        format!("{}{}{}", header_str, URL_SAFE_NO_PAD.encode(payload), rest)
+    }
+
+    #[test]
+    fn local_round_trip() {
+        let key = [0x42u8; 32];
+        let claims = Claims {
+            sub: "alice".to_string(),
+        };
+
+        let token = encrypt_local(&claims, &key, None).unwrap();
+        let decrypted: Claims = decrypt_local(&token, &key, None, &no_time_checks()).unwrap();
+
+        assert_eq!(claims, decrypted);
+    }
+
+    #[test]
+    fn local_round_trip_with_footer() {
+        let key = [0x99u8; 32];
+        let claims = Claims {
+            sub: "bob".to_string(),
+        };
+        let footer = b"kid:test-key";
+
+        let token = encrypt_local(&claims, &key, Some(footer)).unwrap();
+        let decrypted: Claims =
+            decrypt_local(&token, &key, Some(footer), &no_time_checks()).unwrap();
+
+        assert_eq!(claims, decrypted);
+    }
+
+    #[test]
+    fn local_rejects_tampered_tag() {
+        let key = [0x11u8; 32];
+        let claims = Claims {
+            sub: "carol".to_string(),
+        };
+
+        let token = encrypt_local(&claims, &key, None).unwrap();
+        let tampered = corrupt_local_payload(&token, 0); // last byte falls inside the tag
+
+        let result: Result<Claims, String> = decrypt_local(&tampered, &key, None, &no_time_checks());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn local_rejects_tampered_ciphertext() {
+        let key = [0x22u8; 32];
+        let claims = Claims {
+            sub: "dave".to_string(),
+        };
+
+        let token = encrypt_local(&claims, &key, None).unwrap();
+        // 32-byte tag + a few bytes of plaintext puts this offset inside the ciphertext.
+        let tampered = corrupt_local_payload(&token, 40);
+
+        let result: Result<Claims, String> = decrypt_local(&tampered, &key, None, &no_time_checks());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn local_rejects_wrong_key() {
+        let key = [0x33u8; 32];
+        let wrong_key = [0x44u8; 32];
+        let claims = Claims {
+            sub: "erin".to_string(),
+        };
+
+        let token = encrypt_local(&claims, &key, None).unwrap();
+        let result: Result<Claims, String> =
+            decrypt_local(&token, &wrong_key, None, &no_time_checks());
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// Binds a user's password to their signing key (and any `v4.local`
+/// symmetric key) so both are sealed at rest and only unsealed on login.
+/// Previously `hash_password` and `generate_keypair` were unrelated: the
+/// password only produced an Argon2 verifier hash, while private keys lived
+/// as plaintext hex wherever the caller stored them.
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const CRYPTO_ROOT_PREFIX: &str = "vaux:cryptoroot:pass:";
+
+#[derive(Serialize, Deserialize)]
+struct VaultSecrets {
+    signing_key_hex: String,
+    local_key_hex: Option<String>,
+}
+
+/// Keys recovered after a successful `unseal_keys` call.
+pub struct UnsealedKeys {
+    pub signing_key_hex: String,
+    pub local_key: Option<[u8; 32]>,
+}
+
+/// Seals `signing_key_hex` (and an optional `v4.local` key) under a key
+/// derived from `password`, returning a self-describing `CryptoRoot` string
+/// suitable for storage by the `database`/`state` layers.
+pub fn seal_keys(
+    password: &str,
+    signing_key_hex: &str,
+    local_key: Option<&[u8; 32]>,
+) -> Result<String, String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let wrapping_key = derive_wrapping_key(password, &salt)?;
+
+    let secrets = VaultSecrets {
+        signing_key_hex: signing_key_hex.to_string(),
+        local_key_hex: local_key.map(hex::encode),
+    };
+    let plaintext = serde_json::to_vec(&secrets).map_err(|e| e.to_string())?;
+
+    let mut nonce_bytes = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce_bytes);
+
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&wrapping_key));
+    let ciphertext = cipher
+        .encrypt(XNonce::from_slice(&nonce_bytes), plaintext.as_slice())
+        .map_err(|_| "Failed to seal vault".to_string())?;
+
+    let mut blob = Vec::with_capacity(SALT_LEN + NONCE_LEN + ciphertext.len());
+    blob.extend_from_slice(&salt);
+    blob.extend_from_slice(&nonce_bytes);
+    blob.extend_from_slice(&ciphertext);
+
+    Ok(format!("{CRYPTO_ROOT_PREFIX}{}", STANDARD.encode(blob)))
+}
+
+/// Unseals a `CryptoRoot` produced by `seal_keys`. A wrong password surfaces
+/// as an AEAD authentication failure rather than a generic parse error.
+pub fn unseal_keys(password: &str, crypto_root: &str) -> Result<UnsealedKeys, String> {
+    let encoded = crypto_root
+        .strip_prefix(CRYPTO_ROOT_PREFIX)
+        .ok_or_else(|| "Not a recognized CryptoRoot".to_string())?;
+    let blob = STANDARD.decode(encoded).map_err(|e| e.to_string())?;
+    if blob.len() < SALT_LEN + NONCE_LEN {
+        return Err("Malformed CryptoRoot".to_string());
+    }
+
+    let salt = &blob[0..SALT_LEN];
+    let nonce_bytes = &blob[SALT_LEN..SALT_LEN + NONCE_LEN];
+    let ciphertext = &blob[SALT_LEN + NONCE_LEN..];
+
+    let wrapping_key = derive_wrapping_key(password, salt)?;
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(&wrapping_key));
+    let plaintext = cipher
+        .decrypt(XNonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|_| "Incorrect password".to_string())?;
+
+    let secrets: VaultSecrets = serde_json::from_slice(&plaintext).map_err(|e| e.to_string())?;
+    let local_key = match secrets.local_key_hex {
+        Some(hex_key) => {
+            let bytes = hex::decode(hex_key).map_err(|e| e.to_string())?;
+            let array: [u8; 32] = bytes
+                .try_into()
+                .map_err(|_| "Invalid local key length".to_string())?;
+            Some(array)
+        }
+        None => None,
+    };
+
+    Ok(UnsealedKeys {
+        signing_key_hex: secrets.signing_key_hex,
+        local_key,
+    })
+}
+
+/// Re-wraps the same underlying keys under a new password-derived key,
+/// without changing the user's identity keys.
+pub fn rotate_password(
+    old_password: &str,
+    new_password: &str,
+    crypto_root: &str,
+) -> Result<String, String> {
+    let unsealed = unseal_keys(old_password, crypto_root)?;
+    seal_keys(
+        new_password,
+        &unsealed.signing_key_hex,
+        unsealed.local_key.as_ref(),
+    )
+}
+
+/// Derives a 32-byte wrapping key from a password and salt using Argon2id,
+/// with the same m=64MiB/t=3/p=4 parameters as `auth::hash_password`.
+fn derive_wrapping_key(password: &str, salt: &[u8]) -> Result<[u8; 32], String> {
+    let params = argon2::Params::new(65536, 3, 4, Some(32)).map_err(|e| e.to_string())?;
+    let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+    let mut wrapping_key = [0u8; 32];
+    argon2
+        .hash_password_into(password.as_bytes(), salt, &mut wrapping_key)
+        .map_err(|e| e.to_string())?;
+    Ok(wrapping_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_unseal_round_trip() {
+        let signing_key_hex = "aa".repeat(64);
+        let local_key = [0x7fu8; 32];
+
+        let sealed = seal_keys("correct horse battery staple", &signing_key_hex, Some(&local_key))
+            .unwrap();
+        let unsealed = unseal_keys("correct horse battery staple", &sealed).unwrap();
+
+        assert_eq!(unsealed.signing_key_hex, signing_key_hex);
+        assert_eq!(unsealed.local_key, Some(local_key));
+    }
+
+    #[test]
+    fn seal_unseal_round_trip_without_local_key() {
+        let signing_key_hex = "bb".repeat(64);
+
+        let sealed = seal_keys("a password", &signing_key_hex, None).unwrap();
+        let unsealed = unseal_keys("a password", &sealed).unwrap();
+
+        assert_eq!(unsealed.signing_key_hex, signing_key_hex);
+        assert_eq!(unsealed.local_key, None);
+    }
+
+    #[test]
+    fn unseal_rejects_wrong_password() {
+        let sealed = seal_keys("right password", &"cc".repeat(64), None).unwrap();
+
+        let result = unseal_keys("wrong password", &sealed);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unseal_rejects_tampered_ciphertext() {
+        let sealed = seal_keys("a password", &"dd".repeat(64), None).unwrap();
+        let tampered = corrupt_crypto_root(&sealed);
+
+        let result = unseal_keys("a password", &tampered);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn rotate_password_round_trip() {
+        let signing_key_hex = "ee".repeat(64);
+        let local_key = [0x11u8; 32];
+
+        let sealed = seal_keys("old password", &signing_key_hex, Some(&local_key)).unwrap();
+        let rotated = rotate_password("old password", "new password", &sealed).unwrap();
+
+        assert!(unseal_keys("old password", &rotated).is_err());
+
+        let unsealed = unseal_keys("new password", &rotated).unwrap();
+        assert_eq!(unsealed.signing_key_hex, signing_key_hex);
+        assert_eq!(unsealed.local_key, Some(local_key));
+    }
+
+    /// Flips the last byte of the sealed ciphertext (the AEAD tag lives at
+    /// the end), leaving the salt and nonce untouched.
+    fn corrupt_crypto_root(crypto_root: &str) -> String {
+        let encoded = crypto_root.strip_prefix(CRYPTO_ROOT_PREFIX).unwrap();
+        let mut blob = STANDARD.decode(encoded).unwrap();
+        let last = blob.len() - 1;
+        blob[last] ^= 0x01;
+        format!("{CRYPTO_ROOT_PREFIX}{}", STANDARD.encode(blob))
+    }
+}